@@ -69,17 +69,50 @@ pub enum XmlRole {
     IgnoreSect,
     #[cfg(feature = "DTD")]
     InnerParamEntityRef,
+    #[cfg(feature = "DTD")]
+    ContentParamEntityRef,
     ParamEntityRef,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PrologError {
+    UnexpectedToken,
+    MissingDoctypeName,
+    BadEntityDecl,
+    BadAttlistType,
+    BadNotationDecl,
+    BadElementContent,
+    MisplacedConditionalClose,
+    MixedGroupConnectors,
+    GroupDepthExceeded,
+}
+
+/// Bounds content-model group nesting so a hostile DTD can't force
+/// unbounded recursion in a consumer building a model tree.
+const DEFAULT_MAX_GROUP_DEPTH: u32 = 1000;
+
 type PrologHandler = fn(&mut PrologState, XmlTok, &str, &str, &Encoding) -> XmlRole;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum GroupConnector {
+    Sequence,
+    Choice,
+}
+
 pub struct PrologState {
     handler: PrologHandler,
     level: u32,
     role_none: XmlRole,
+    group_connectors: Vec<Option<GroupConnector>>,
+    last_error: Option<PrologError>,
+    enum_tokens: Vec<String>,
+    max_group_depth: u32,
     #[cfg(feature = "DTD")]
     include_level: u32,
+    /// Nesting depth of conditional sections opened while skipping the
+    /// body of an `IGNORE`d section; zero means the next `]]>` ends it.
+    #[cfg(feature = "DTD")]
+    ignore_level: u32,
     #[cfg(feature = "DTD")]
     document_entity: bool,
     #[cfg(feature = "DTD")]
@@ -92,15 +125,61 @@ impl PrologState {
             handler: prolog0,
             level: 0,
             role_none: XmlRole::None,
+            group_connectors: Vec::new(),
+            last_error: None,
+            enum_tokens: Vec::new(),
+            max_group_depth: DEFAULT_MAX_GROUP_DEPTH,
             #[cfg(feature = "DTD")]
             document_entity: true,
             #[cfg(feature = "DTD")]
             include_level: 0,
             #[cfg(feature = "DTD")]
+            ignore_level: 0,
+            #[cfg(feature = "DTD")]
             in_entity_value: false,
         }
     }
 
+    fn push_group(&mut self) -> bool {
+        if self.group_connectors.len() as u32 >= self.max_group_depth {
+            return false;
+        }
+        self.group_connectors.push(None);
+        true
+    }
+
+    fn pop_group(&mut self) {
+        self.group_connectors.pop();
+    }
+
+    pub fn set_max_group_depth(&mut self, max_group_depth: u32) {
+        self.max_group_depth = max_group_depth;
+    }
+
+    fn check_connector(&mut self, connector: GroupConnector) -> bool {
+        match self.group_connectors.last_mut() {
+            Some(slot @ None) => {
+                *slot = Some(connector);
+                true
+            }
+            Some(Some(existing)) => *existing == connector,
+            None => true,
+        }
+    }
+
+    fn reset_enum_tokens(&mut self) {
+        self.enum_tokens.clear();
+    }
+
+    fn record_enum_token(&mut self, text: &str) -> bool {
+        if self.enum_tokens.iter().any(|seen| seen == text) {
+            false
+        } else {
+            self.enum_tokens.push(text.to_string());
+            true
+        }
+    }
+
     #[cfg(feature = "DTD")]
     pub fn new_external_entity() -> Self {
         PrologState {
@@ -114,6 +193,10 @@ impl PrologState {
     pub fn token_role(&mut self, tok: XmlTok, ptr: &str, end: &str, enc: &Encoding) -> XmlRole {
         (self.handler)(self, tok, ptr, end, enc)
     }
+
+    pub fn last_error(&self) -> Option<PrologError> {
+        self.last_error
+    }
 }
 
 const KW_ANY: &str = "ANY";
@@ -168,7 +251,7 @@ fn prolog0(state: &mut PrologState, tok: XmlTok, ptr: &str, end: &str, enc: &Enc
                 end,
                 KW_DOCTYPE,
             ) {
-                common(state, tok)
+                common(state, tok, PrologError::UnexpectedToken)
             } else {
                 state.handler = doctype0;
                 XmlRole::DoctypeNone
@@ -178,7 +261,7 @@ fn prolog0(state: &mut PrologState, tok: XmlTok, ptr: &str, end: &str, enc: &Enc
             state.handler = error;
             XmlRole::InstanceStart
         }
-        _ => common(state, tok),
+        _ => common(state, tok, PrologError::UnexpectedToken),
     }
 }
 
@@ -195,7 +278,7 @@ fn prolog1(state: &mut PrologState, tok: XmlTok, ptr: &str, end: &str, enc: &Enc
                 end,
                 KW_DOCTYPE,
             ) {
-                common(state, tok)
+                common(state, tok, PrologError::UnexpectedToken)
             } else {
                 state.handler = doctype0;
                 XmlRole::DoctypeNone
@@ -205,7 +288,7 @@ fn prolog1(state: &mut PrologState, tok: XmlTok, ptr: &str, end: &str, enc: &Enc
             state.handler = error;
             XmlRole::InstanceStart
         }
-        _ => common(state, tok),
+        _ => common(state, tok, PrologError::UnexpectedToken),
     }
 }
 
@@ -218,7 +301,7 @@ fn prolog2(state: &mut PrologState, tok: XmlTok, _ptr: &str, _end: &str, _enc: &
             state.handler = error;
             XmlRole::InstanceStart
         }
-        _ => common(state, tok),
+        _ => common(state, tok, PrologError::UnexpectedToken),
     }
 }
 
@@ -229,7 +312,7 @@ fn doctype0(state: &mut PrologState, tok: XmlTok, _ptr: &str, _end: &str, _enc:
             state.handler = doctype1;
             XmlRole::DoctypeName
         }
-        _ => common(state, tok),
+        _ => common(state, tok, PrologError::MissingDoctypeName),
     }
 }
 
@@ -252,10 +335,10 @@ fn doctype1(state: &mut PrologState, tok: XmlTok, ptr: &str, end: &str, enc: &En
                 state.handler = doctype2;
                 XmlRole::DoctypeNone
             } else {
-                common(state, tok)
+                common(state, tok, PrologError::UnexpectedToken)
             }
         }
-        _ => common(state, tok),
+        _ => common(state, tok, PrologError::UnexpectedToken),
     }
 }
 
@@ -266,7 +349,7 @@ fn doctype2(state: &mut PrologState, tok: XmlTok, _ptr: &str, _end: &str, _enc:
             state.handler = doctype3;
             XmlRole::DoctypePublicId
         }
-        _ => common(state, tok),
+        _ => common(state, tok, PrologError::UnexpectedToken),
     }
 }
 
@@ -277,7 +360,7 @@ fn doctype3(state: &mut PrologState, tok: XmlTok, _ptr: &str, _end: &str, _enc:
             state.handler = doctype4;
             XmlRole::DoctypeSystemId
         }
-        _ => common(state, tok),
+        _ => common(state, tok, PrologError::UnexpectedToken),
     }
 }
 
@@ -292,7 +375,7 @@ fn doctype4(state: &mut PrologState, tok: XmlTok, _ptr: &str, _end: &str, _enc:
             state.handler = prolog2;
             XmlRole::DoctypeClose
         }
-        _ => common(state, tok),
+        _ => common(state, tok, PrologError::UnexpectedToken),
     }
 }
 
@@ -303,7 +386,7 @@ fn doctype5(state: &mut PrologState, tok: XmlTok, _ptr: &str, _end: &str, _enc:
             state.handler = prolog2;
             XmlRole::DoctypeClose
         }
-        _ => common(state, tok),
+        _ => common(state, tok, PrologError::UnexpectedToken),
     }
 }
 
@@ -325,7 +408,7 @@ fn internal_subset(state: &mut PrologState, tok: XmlTok, ptr: &str, end: &str, e
                 state.handler = notation0;
                 XmlRole::NotationNone
             } else {
-                common(state, tok)
+                common(state, tok, PrologError::UnexpectedToken)
             }
         }
         XmlTok::Pi => XmlRole::Pi,
@@ -336,7 +419,7 @@ fn internal_subset(state: &mut PrologState, tok: XmlTok, ptr: &str, end: &str, e
             XmlRole::DoctypeNone
         }
         XmlTok::None => XmlRole::None,
-        _ => common(state, tok),
+        _ => common(state, tok, PrologError::UnexpectedToken),
     }
 }
 
@@ -359,17 +442,17 @@ fn external_subset1(state: &mut PrologState, tok: XmlTok, ptr: &str, end: &str,
         }
         XmlTok::CondSectClose => {
             if state.include_level == 0 {
-                common(state, tok)
+                common(state, tok, PrologError::MisplacedConditionalClose)
             } else {
                 state.include_level -= 1;
                 XmlRole::None
             }
         }
         XmlTok::PrologS => XmlRole::None,
-        XmlTok::CloseBracket => common(state, tok),
+        XmlTok::CloseBracket => common(state, tok, PrologError::MisplacedConditionalClose),
         XmlTok::None => {
             if state.include_level != 0 {
-                common(state, tok)
+                common(state, tok, PrologError::MisplacedConditionalClose)
             } else {
                 XmlRole::None
             }
@@ -389,7 +472,7 @@ fn entity0(state: &mut PrologState, tok: XmlTok, _ptr: &str, _end: &str, _enc: &
             state.handler = entity2;
             XmlRole::GeneralEntityName
         }
-        _ => common(state, tok),
+        _ => common(state, tok, PrologError::BadEntityDecl),
     }
 }
 
@@ -400,7 +483,7 @@ fn entity1(state: &mut PrologState, tok: XmlTok, _ptr: &str, _end: &str, _enc: &
             state.handler = entity7;
             XmlRole::ParamEntityName
         }
-        _ => common(state, tok),
+        _ => common(state, tok, PrologError::BadEntityDecl),
     }
 }
 
@@ -415,7 +498,7 @@ fn entity2(state: &mut PrologState, tok: XmlTok, ptr: &str, end: &str, enc: &Enc
                 state.handler = entity3;
                 XmlRole::EntityNone
             } else {
-                common(state, tok)
+                common(state, tok, PrologError::BadEntityDecl)
             }
         }
         XmlTok::Literal => {
@@ -423,7 +506,7 @@ fn entity2(state: &mut PrologState, tok: XmlTok, ptr: &str, end: &str, enc: &Enc
             state.role_none = XmlRole::EntityNone;
             XmlRole::EntityValue
         }
-        _ => common(state, tok),
+        _ => common(state, tok, PrologError::BadEntityDecl),
     }
 }
 
@@ -434,7 +517,7 @@ fn entity3(state: &mut PrologState, tok: XmlTok, _ptr: &str, _end: &str, _enc: &
             state.handler = entity4;
             XmlRole::EntityPublicId
         }
-        _ => common(state, tok),
+        _ => common(state, tok, PrologError::BadEntityDecl),
     }
 }
 
@@ -445,7 +528,7 @@ fn entity4(state: &mut PrologState, tok: XmlTok, _ptr: &str, _end: &str, _enc: &
             state.handler = entity5;
             XmlRole::EntitySystemId
         }
-        _ => common(state, tok),
+        _ => common(state, tok, PrologError::BadEntityDecl),
     }
 }
 
@@ -461,10 +544,10 @@ fn entity5(state: &mut PrologState, tok: XmlTok, ptr: &str, end: &str, enc: &Enc
                 state.handler = entity6;
                 XmlRole::EntityNone
             } else {
-                common(state, tok)
+                common(state, tok, PrologError::BadEntityDecl)
             }
         }
-        _ => common(state, tok),
+        _ => common(state, tok, PrologError::BadEntityDecl),
     }
 }
 
@@ -476,7 +559,7 @@ fn entity6(state: &mut PrologState, tok: XmlTok, _ptr: &str, _end: &str, _enc: &
             state.role_none = XmlRole::EntityNone;
             XmlRole::EntityNotationName
         }
-        _ => common(state, tok),
+        _ => common(state, tok, PrologError::BadEntityDecl),
     }
 }
 
@@ -491,7 +574,7 @@ fn entity7(state: &mut PrologState, tok: XmlTok, ptr: &str, end: &str, enc: &Enc
                 state.handler = entity8;
                 XmlRole::EntityNone
             } else {
-                common(state, tok)
+                common(state, tok, PrologError::BadEntityDecl)
             }
         }
         XmlTok::Literal => {
@@ -499,7 +582,7 @@ fn entity7(state: &mut PrologState, tok: XmlTok, ptr: &str, end: &str, enc: &Enc
             state.role_none = XmlRole::EntityNone;
             XmlRole::EntityValue
         }
-        _ => common(state, tok),
+        _ => common(state, tok, PrologError::BadEntityDecl),
     }
 }
 
@@ -510,7 +593,7 @@ fn entity8(state: &mut PrologState, tok: XmlTok, _ptr: &str, _end: &str, _enc: &
             state.handler = entity9;
             XmlRole::EntityPublicId
         }
-        _ => common(state, tok),
+        _ => common(state, tok, PrologError::BadEntityDecl),
     }
 }
 
@@ -521,7 +604,7 @@ fn entity9(state: &mut PrologState, tok: XmlTok, _ptr: &str, _end: &str, _enc: &
             state.handler = entity10;
             XmlRole::EntitySystemId
         }
-        _ => common(state, tok),
+        _ => common(state, tok, PrologError::BadEntityDecl),
     }
 }
 
@@ -532,7 +615,7 @@ fn entity10(state: &mut PrologState, tok: XmlTok, _ptr: &str, _end: &str, _enc:
             set_top_level(state);
             XmlRole::EntityComplete
         }
-        _ => common(state, tok),
+        _ => common(state, tok, PrologError::BadEntityDecl),
     }
 }
 
@@ -543,7 +626,7 @@ fn notation0(state: &mut PrologState, tok: XmlTok, _ptr: &str, _end: &str, _enc:
             state.handler = notation1;
             XmlRole::NotationName
         }
-        _ => common(state, tok),
+        _ => common(state, tok, PrologError::BadNotationDecl),
     }
 }
 
@@ -558,10 +641,10 @@ fn notation1(state: &mut PrologState, tok: XmlTok, ptr: &str, end: &str, enc: &E
                 state.handler = notation2;
                 XmlRole::NotationNone
             } else {
-                common(state, tok)
+                common(state, tok, PrologError::BadNotationDecl)
             }
         }
-        _ => common(state, tok),
+        _ => common(state, tok, PrologError::BadNotationDecl),
     }
 }
 
@@ -572,7 +655,7 @@ fn notation2(state: &mut PrologState, tok: XmlTok, _ptr: &str, _end: &str, _enc:
             state.handler = notation4;
             XmlRole::NotationPublicId
         }
-        _ => common(state, tok),
+        _ => common(state, tok, PrologError::BadNotationDecl),
     }
 }
 
@@ -584,7 +667,7 @@ fn notation3(state: &mut PrologState, tok: XmlTok, _ptr: &str, _end: &str, _enc:
             state.role_none = XmlRole::NotationNone;
             XmlRole::NotationSystemId
         }
-        _ => common(state, tok),
+        _ => common(state, tok, PrologError::BadNotationDecl),
     }
 }
 
@@ -600,7 +683,7 @@ fn notation4(state: &mut PrologState, tok: XmlTok, _ptr: &str, _end: &str, _enc:
             set_top_level(state);
             XmlRole::NotationNoSystemId
         }
-        _ => common(state, tok),
+        _ => common(state, tok, PrologError::BadNotationDecl),
     }
 }
 
@@ -611,7 +694,7 @@ fn attlist0(state: &mut PrologState, tok: XmlTok, _ptr: &str, _end: &str, _enc:
             state.handler = attlist1;
             XmlRole::AttlistElementName
         }
-        _ => common(state, tok),
+        _ => common(state, tok, PrologError::BadAttlistType),
     }
 }
 
@@ -626,7 +709,7 @@ fn attlist1(state: &mut PrologState, tok: XmlTok, _ptr: &str, _end: &str, _enc:
             state.handler = attlist2;
             XmlRole::AttributeName
         }
-        _ => common(state, tok),
+        _ => common(state, tok, PrologError::BadAttlistType),
     }
 }
 
@@ -648,25 +731,31 @@ fn attlist2(state: &mut PrologState, tok: XmlTok, ptr: &str, end: &str, enc: &En
                 state.handler = attlist5;
                 XmlRole::AttlistNone
             } else {
-                common(state, tok)
+                common(state, tok, PrologError::BadAttlistType)
             }
         }
         XmlTok::OpenParen => {
             state.handler = attlist3;
+            state.reset_enum_tokens();
             XmlRole::AttlistNone
         }
-        _ => common(state, tok),
+        _ => common(state, tok, PrologError::BadAttlistType),
     }
 }
 
-fn attlist3(state: &mut PrologState, tok: XmlTok, _ptr: &str, _end: &str, _enc: &Encoding) -> XmlRole {
+fn attlist3(state: &mut PrologState, tok: XmlTok, ptr: &str, end: &str, enc: &Encoding) -> XmlRole {
     match tok {
         XmlTok::PrologS => XmlRole::AttlistNone,
         XmlTok::Nmtoken | XmlTok::Name | XmlTok::PrefixedName => {
+            if !state.record_enum_token(enum_token_text(ptr, end, enc)) {
+                state.handler = error;
+                state.last_error = Some(PrologError::BadAttlistType);
+                return XmlRole::Error;
+            }
             state.handler = attlist4;
             XmlRole::AttributeEnumValue
         }
-        _ => common(state, tok),
+        _ => common(state, tok, PrologError::BadAttlistType),
     }
 }
 
@@ -681,7 +770,7 @@ fn attlist4(state: &mut PrologState, tok: XmlTok, _ptr: &str, _end: &str, _enc:
             state.handler = attlist3;
             XmlRole::AttlistNone
         }
-        _ => common(state, tok),
+        _ => common(state, tok, PrologError::BadAttlistType),
     }
 }
 
@@ -690,20 +779,26 @@ fn attlist5(state: &mut PrologState, tok: XmlTok, _ptr: &str, _end: &str, _enc:
         XmlTok::PrologS => XmlRole::AttlistNone,
         XmlTok::OpenParen => {
             state.handler = attlist6;
+            state.reset_enum_tokens();
             XmlRole::AttlistNone
         }
-        _ => common(state, tok),
+        _ => common(state, tok, PrologError::BadAttlistType),
     }
 }
 
-fn attlist6(state: &mut PrologState, tok: XmlTok, _ptr: &str, _end: &str, _enc: &Encoding) -> XmlRole {
+fn attlist6(state: &mut PrologState, tok: XmlTok, ptr: &str, end: &str, enc: &Encoding) -> XmlRole {
     match tok {
         XmlTok::PrologS => XmlRole::AttlistNone,
         XmlTok::Name => {
+            if !state.record_enum_token(enum_token_text(ptr, end, enc)) {
+                state.handler = error;
+                state.last_error = Some(PrologError::BadAttlistType);
+                return XmlRole::Error;
+            }
             state.handler = attlist7;
             XmlRole::AttributeNotationValue
         }
-        _ => common(state, tok),
+        _ => common(state, tok, PrologError::BadAttlistType),
     }
 }
 
@@ -718,7 +813,7 @@ fn attlist7(state: &mut PrologState, tok: XmlTok, _ptr: &str, _end: &str, _enc:
             state.handler = attlist6;
             XmlRole::AttlistNone
         }
-        _ => common(state, tok),
+        _ => common(state, tok, PrologError::BadAttlistType),
     }
 }
 
@@ -737,14 +832,14 @@ fn attlist8(state: &mut PrologState, tok: XmlTok, ptr: &str, end: &str, enc: &En
                 state.handler = attlist9;
                 XmlRole::AttlistNone
             } else {
-                common(state, tok)
+                common(state, tok, PrologError::BadAttlistType)
             }
         }
         XmlTok::Literal => {
             state.handler = attlist1;
             XmlRole::DefaultAttributeValue
         }
-        _ => common(state, tok),
+        _ => common(state, tok, PrologError::BadAttlistType),
     }
 }
 
@@ -755,7 +850,7 @@ fn attlist9(state: &mut PrologState, tok: XmlTok, _ptr: &str, _end: &str, _enc:
             state.handler = attlist1;
             XmlRole::FixedAttributeValue
         }
-        _ => common(state, tok),
+        _ => common(state, tok, PrologError::BadAttlistType),
     }
 }
 
@@ -766,7 +861,7 @@ fn element0(state: &mut PrologState, tok: XmlTok, _ptr: &str, _end: &str, _enc:
             state.handler = element1;
             XmlRole::ElementName
         }
-        _ => common(state, tok),
+        _ => common(state, tok, PrologError::BadElementContent),
     }
 }
 
@@ -783,15 +878,21 @@ fn element1(state: &mut PrologState, tok: XmlTok, ptr: &str, end: &str, enc: &En
                 state.role_none = XmlRole::ElementNone;
                 XmlRole::ContentAny
             } else {
-                common(state, tok)
+                common(state, tok, PrologError::BadElementContent)
             }
         }
         XmlTok::OpenParen => {
-            state.handler = element2;
             state.level = 1;
+            state.group_connectors.clear();
+            if !state.push_group() {
+                state.handler = error;
+                state.last_error = Some(PrologError::GroupDepthExceeded);
+                return XmlRole::Error;
+            }
+            state.handler = element2;
             XmlRole::GroupOpen
         }
-        _ => common(state, tok),
+        _ => common(state, tok, PrologError::BadElementContent),
     }
 }
 
@@ -808,11 +909,16 @@ fn element2(state: &mut PrologState, tok: XmlTok, ptr: &str, end: &str, enc: &En
                 state.handler = element3;
                 XmlRole::ContentPcdata
             } else {
-                common(state, tok)
+                common(state, tok, PrologError::BadElementContent)
             }
         }
         XmlTok::OpenParen => {
             state.level = 2;
+            if !state.push_group() {
+                state.handler = error;
+                state.last_error = Some(PrologError::GroupDepthExceeded);
+                return XmlRole::Error;
+            }
             state.handler = element6;
             XmlRole::GroupOpen
         }
@@ -832,7 +938,9 @@ fn element2(state: &mut PrologState, tok: XmlTok, ptr: &str, end: &str, enc: &En
             state.handler = element7;
             XmlRole::ContentElementPlus
         }
-        _ => common(state, tok),
+        #[cfg(feature = "DTD")]
+        XmlTok::ParamEntityRef if !state.document_entity => XmlRole::ContentParamEntityRef,
+        _ => common(state, tok, PrologError::BadElementContent),
     }
 }
 
@@ -840,11 +948,13 @@ fn element3(state: &mut PrologState, tok: XmlTok, _ptr: &str, _end: &str, _enc:
     match tok {
         XmlTok::PrologS => XmlRole::ElementNone,
         XmlTok::CloseParen => {
+            state.pop_group();
             state.handler = decl_close;
             state.role_none = XmlRole::ElementNone;
             XmlRole::GroupClose
         }
         XmlTok::CloseParenAsterisk => {
+            state.pop_group();
             state.handler = decl_close;
             state.role_none = XmlRole::ElementNone;
             XmlRole::GroupCloseRep
@@ -853,7 +963,7 @@ fn element3(state: &mut PrologState, tok: XmlTok, _ptr: &str, _end: &str, _enc:
             state.handler = element4;
             XmlRole::ElementNone
         }
-        _ => common(state, tok),
+        _ => common(state, tok, PrologError::BadElementContent),
     }
 }
 
@@ -864,7 +974,9 @@ fn element4(state: &mut PrologState, tok: XmlTok, _ptr: &str, _end: &str, _enc:
             state.handler = element5;
             XmlRole::ContentElement
         }
-        _ => common(state, tok),
+        #[cfg(feature = "DTD")]
+        XmlTok::ParamEntityRef if !state.document_entity => XmlRole::ContentParamEntityRef,
+        _ => common(state, tok, PrologError::BadElementContent),
     }
 }
 
@@ -880,7 +992,7 @@ fn element5(state: &mut PrologState, tok: XmlTok, _ptr: &str, _end: &str, _enc:
             state.handler = element4;
             XmlRole::ElementNone
         }
-        _ => common(state, tok),
+        _ => common(state, tok, PrologError::BadElementContent),
     }
 }
 
@@ -889,6 +1001,11 @@ fn element6(state: &mut PrologState, tok: XmlTok, _ptr: &str, _end: &str, _enc:
         XmlTok::PrologS => XmlRole::ElementNone,
         XmlTok::OpenParen => {
             state.level += 1;
+            if !state.push_group() {
+                state.handler = error;
+                state.last_error = Some(PrologError::GroupDepthExceeded);
+                return XmlRole::Error;
+            }
             XmlRole::GroupOpen
         }
         XmlTok::Name | XmlTok::PrefixedName => {
@@ -907,7 +1024,9 @@ fn element6(state: &mut PrologState, tok: XmlTok, _ptr: &str, _end: &str, _enc:
             state.handler = element7;
             XmlRole::ContentElementPlus
         }
-        _ => common(state, tok),
+        #[cfg(feature = "DTD")]
+        XmlTok::ParamEntityRef if !state.document_entity => XmlRole::ContentParamEntityRef,
+        _ => common(state, tok, PrologError::BadElementContent),
     }
 }
 
@@ -915,6 +1034,7 @@ fn element7(state: &mut PrologState, tok: XmlTok, _ptr: &str, _end: &str, _enc:
     match tok {
         XmlTok::PrologS => XmlRole::ElementNone,
         XmlTok::CloseParen => {
+            state.pop_group();
             state.level -= 1;
             if state.level == 0 {
                 state.handler = decl_close;
@@ -923,6 +1043,7 @@ fn element7(state: &mut PrologState, tok: XmlTok, _ptr: &str, _end: &str, _enc:
             XmlRole::GroupClose
         }
         XmlTok::CloseParenAsterisk => {
+            state.pop_group();
             state.level -= 1;
             if state.level == 0 {
                 state.handler = decl_close;
@@ -931,6 +1052,7 @@ fn element7(state: &mut PrologState, tok: XmlTok, _ptr: &str, _end: &str, _enc:
             XmlRole::GroupCloseRep
         }
         XmlTok::CloseParenQuestion => {
+            state.pop_group();
             state.level -= 1;
             if state.level == 0 {
                 state.handler = decl_close;
@@ -939,6 +1061,7 @@ fn element7(state: &mut PrologState, tok: XmlTok, _ptr: &str, _end: &str, _enc:
             XmlRole::GroupCloseOpt
         }
         XmlTok::CloseParenPlus => {
+            state.pop_group();
             state.level -= 1;
             if state.level == 0 {
                 state.handler = decl_close;
@@ -947,14 +1070,26 @@ fn element7(state: &mut PrologState, tok: XmlTok, _ptr: &str, _end: &str, _enc:
             XmlRole::GroupClosePlus
         }
         XmlTok::Comma => {
+            if !state.check_connector(GroupConnector::Sequence) {
+                state.handler = error;
+                state.last_error = Some(PrologError::MixedGroupConnectors);
+                return XmlRole::Error;
+            }
             state.handler = element6;
             XmlRole::GroupSequence
         }
         XmlTok::Or => {
+            if !state.check_connector(GroupConnector::Choice) {
+                state.handler = error;
+                state.last_error = Some(PrologError::MixedGroupConnectors);
+                return XmlRole::Error;
+            }
             state.handler = element6;
             XmlRole::GroupChoice
         }
-        _ => common(state, tok),
+        #[cfg(feature = "DTD")]
+        XmlTok::ParamEntityRef if !state.document_entity => XmlRole::ContentParamEntityRef,
+        _ => common(state, tok, PrologError::BadElementContent),
     }
 }
 
@@ -970,10 +1105,10 @@ fn cond_sect0(state: &mut PrologState, tok: XmlTok, ptr: &str, end: &str, enc: &
                 state.handler = cond_sect2;
                 XmlRole::None
             } else {
-                common(state, tok)
+                common(state, tok, PrologError::MisplacedConditionalClose)
             }
         }
-        _ => common(state, tok),
+        _ => common(state, tok, PrologError::MisplacedConditionalClose),
     }
 }
 
@@ -986,7 +1121,7 @@ fn cond_sect1(state: &mut PrologState, tok: XmlTok, _ptr: &str, _end: &str, _enc
             state.include_level += 1;
             XmlRole::None
         }
-        _ => common(state, tok),
+        _ => common(state, tok, PrologError::MisplacedConditionalClose),
     }
 }
 
@@ -995,10 +1130,34 @@ fn cond_sect2(state: &mut PrologState, tok: XmlTok, _ptr: &str, _end: &str, _enc
     match tok {
         XmlTok::PrologS => XmlRole::None,
         XmlTok::OpenBracket => {
-            state.handler = external_subset1;
+            state.handler = ignore_sect0;
+            state.ignore_level = 0;
             XmlRole::IgnoreSect
         }
-        _ => common(state, tok),
+        _ => common(state, tok, PrologError::MisplacedConditionalClose),
+    }
+}
+
+/// Consumes the body of an `IGNORE`d conditional section up to its
+/// matching `]]>`, counting nested `<![ ... [` opens so an inner
+/// conditional section's close does not end the outer one prematurely.
+#[cfg(feature = "DTD")]
+fn ignore_sect0(state: &mut PrologState, tok: XmlTok, _ptr: &str, _end: &str, _enc: &Encoding) -> XmlRole {
+    match tok {
+        XmlTok::CondSectOpen => {
+            state.ignore_level += 1;
+            XmlRole::None
+        }
+        XmlTok::CondSectClose => {
+            if state.ignore_level == 0 {
+                state.handler = external_subset1;
+            } else {
+                state.ignore_level -= 1;
+            }
+            XmlRole::None
+        }
+        // Any other token inside the ignored region is skipped outright.
+        _ => XmlRole::None,
     }
 }
 
@@ -1009,20 +1168,25 @@ fn decl_close(state: &mut PrologState, tok: XmlTok, _ptr: &str, _end: &str, _enc
             set_top_level(state);
             state.role_none
         }
-        _ => common(state, tok),
+        _ => common(state, tok, PrologError::UnexpectedToken),
     }
 }
 
+fn enum_token_text<'a>(ptr: &'a str, end: &'a str, enc: &Encoding) -> &'a str {
+    &ptr[..enc.name_length(ptr, end)]
+}
+
 fn error(_state: &mut PrologState, _tok: XmlTok, _ptr: &str, _end: &str, _enc: &Encoding) -> XmlRole {
     XmlRole::None
 }
 
-fn common(state: &mut PrologState, tok: XmlTok) -> XmlRole {
+fn common(state: &mut PrologState, tok: XmlTok, reason: PrologError) -> XmlRole {
     #[cfg(feature = "DTD")]
     if !state.document_entity && tok == XmlTok::ParamEntityRef {
         return XmlRole::InnerParamEntityRef;
     }
     state.handler = error;
+    state.last_error = Some(reason);
     XmlRole::Error
 }
 
@@ -1040,6 +1204,179 @@ fn set_top_level(state: &mut PrologState) {
     }
 }
 
+/// Drives `xmltok`'s prolog tokenizer together with a `PrologState`,
+/// yielding each role alongside the exact source slice of the token.
+pub struct RoleScanner<'a> {
+    state: PrologState,
+    enc: Encoding,
+    ptr: &'a str,
+    end: &'a str,
+    done: bool,
+}
+
+impl<'a> RoleScanner<'a> {
+    pub fn new(buf: &'a str, enc: Encoding) -> Self {
+        RoleScanner {
+            state: PrologState::new(),
+            ptr: buf,
+            end: &buf[buf.len()..],
+            enc,
+            done: false,
+        }
+    }
+
+    pub fn last_error(&self) -> Option<PrologError> {
+        self.state.last_error()
+    }
+}
+
+impl<'a> Iterator for RoleScanner<'a> {
+    type Item = (XmlRole, &'a str);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done || self.ptr.is_empty() {
+            return None;
+        }
+        let (tok, next_ptr) = crate::xmltok::XmlPrologTok(&self.enc, self.ptr, self.end);
+        let token_str = &self.ptr[..self.ptr.len() - next_ptr.len()];
+        let role = self.state.token_role(tok, self.ptr, self.end, &self.enc);
+        self.ptr = next_ptr;
+        if role == XmlRole::Error {
+            self.done = true;
+        }
+        Some((role, token_str))
+    }
+}
+
+pub type Name = String;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContentQuantifier {
+    Once,
+    Optional,
+    Repeated,
+    OneOrMore,
+}
+
+/// A `<!ELEMENT>` content model, expat's `XML_Content` equivalent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ContentModel {
+    Empty,
+    Any,
+    /// `(#PCDATA | a | b)*` and plain `(#PCDATA)`, in declaration order.
+    Mixed(Vec<Name>),
+    Name(Name, ContentQuantifier),
+    Sequence(Vec<ContentModel>, ContentQuantifier),
+    Choice(Vec<ContentModel>, ContentQuantifier),
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+enum PartialGroupKind {
+    #[default]
+    Undetermined,
+    Mixed,
+    Sequence,
+    Choice,
+}
+
+#[derive(Default)]
+struct PartialGroup {
+    kind: PartialGroupKind,
+    children: Vec<ContentModel>,
+}
+
+/// Folds the `XmlRole` stream that `element0`-`element7` produce for a
+/// single `<!ELEMENT>` declaration into a `ContentModel` tree.
+#[derive(Default)]
+pub struct ContentModelBuilder {
+    stack: Vec<PartialGroup>,
+    finished: Option<ContentModel>,
+}
+
+impl ContentModelBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, role: XmlRole, text: &str) {
+        match role {
+            XmlRole::ContentEmpty => self.finished = Some(ContentModel::Empty),
+            XmlRole::ContentAny => self.finished = Some(ContentModel::Any),
+            XmlRole::ContentPcdata => {
+                if let Some(group) = self.stack.last_mut() {
+                    group.kind = PartialGroupKind::Mixed;
+                }
+            }
+            XmlRole::GroupOpen => self.stack.push(PartialGroup::default()),
+            XmlRole::ContentElement => self.push_leaf(text, ContentQuantifier::Once),
+            XmlRole::ContentElementOpt => self.push_leaf(text, ContentQuantifier::Optional),
+            XmlRole::ContentElementRep => self.push_leaf(text, ContentQuantifier::Repeated),
+            XmlRole::ContentElementPlus => self.push_leaf(text, ContentQuantifier::OneOrMore),
+            XmlRole::GroupSequence => self.set_kind(PartialGroupKind::Sequence),
+            XmlRole::GroupChoice => self.set_kind(PartialGroupKind::Choice),
+            XmlRole::GroupClose => self.close_group(ContentQuantifier::Once),
+            XmlRole::GroupCloseOpt => self.close_group(ContentQuantifier::Optional),
+            XmlRole::GroupCloseRep => self.close_group(ContentQuantifier::Repeated),
+            XmlRole::GroupClosePlus => self.close_group(ContentQuantifier::OneOrMore),
+            _ => {}
+        }
+    }
+
+    pub fn content_model(&self) -> Option<&ContentModel> {
+        self.finished.as_ref()
+    }
+
+    fn push_leaf(&mut self, text: &str, quantifier: ContentQuantifier) {
+        // `text` is the token's exact source slice, which for
+        // `ContentElementOpt`/`Rep`/`Plus` includes the trailing
+        // `?`/`*`/`+` suffix; the element name itself does not.
+        let name = match quantifier {
+            ContentQuantifier::Once => text,
+            ContentQuantifier::Optional | ContentQuantifier::Repeated | ContentQuantifier::OneOrMore => {
+                &text[..text.len() - 1]
+            }
+        };
+        let leaf = ContentModel::Name(name.to_string(), quantifier);
+        if let Some(group) = self.stack.last_mut() {
+            group.children.push(leaf);
+        }
+    }
+
+    fn set_kind(&mut self, kind: PartialGroupKind) {
+        if let Some(group) = self.stack.last_mut() {
+            if group.kind == PartialGroupKind::Undetermined {
+                group.kind = kind;
+            }
+        }
+    }
+
+    fn close_group(&mut self, quantifier: ContentQuantifier) {
+        let Some(group) = self.stack.pop() else {
+            return;
+        };
+        let node = match group.kind {
+            PartialGroupKind::Mixed => ContentModel::Mixed(
+                group
+                    .children
+                    .into_iter()
+                    .filter_map(|child| match child {
+                        ContentModel::Name(name, _) => Some(name),
+                        _ => None,
+                    })
+                    .collect(),
+            ),
+            PartialGroupKind::Choice => ContentModel::Choice(group.children, quantifier),
+            PartialGroupKind::Sequence | PartialGroupKind::Undetermined => {
+                ContentModel::Sequence(group.children, quantifier)
+            }
+        };
+        match self.stack.last_mut() {
+            Some(parent) => parent.children.push(node),
+            None => self.finished = Some(node),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1050,4 +1387,402 @@ mod tests {
         let state = PrologState::new_external_entity();
         assert_eq!(state.include_level, 1);
     }
+
+    #[test]
+    #[cfg(feature = "DTD")]
+    fn test_ignore_sect_skips_a_nested_conditional_section_as_one_unit() {
+        // <![IGNORE[ <![IGNORE[ ... ]]> still-ignored ]]>: the inner
+        // close must not end the outer ignored section.
+        let enc = Encoding::utf8();
+        let mut state = PrologState::new();
+        state.handler = cond_sect0;
+        let empty = "";
+        let name_buf = "IGNORE";
+
+        assert_eq!(
+            state.token_role(XmlTok::Name, name_buf, &name_buf[name_buf.len()..], &enc),
+            XmlRole::None
+        );
+        assert_eq!(state.token_role(XmlTok::OpenBracket, empty, empty, &enc), XmlRole::IgnoreSect);
+
+        assert_eq!(state.token_role(XmlTok::CondSectOpen, empty, empty, &enc), XmlRole::None);
+        assert_eq!(state.ignore_level, 1);
+        assert_eq!(state.token_role(XmlTok::CondSectClose, empty, empty, &enc), XmlRole::None);
+        assert_eq!(state.ignore_level, 0);
+
+        // Still inside the outer ignored section: a comment here is
+        // swallowed, not surfaced as a real role.
+        assert_eq!(state.token_role(XmlTok::Comment, empty, empty, &enc), XmlRole::None);
+
+        // The outer close ends it; control is back in external_subset1.
+        assert_eq!(state.token_role(XmlTok::CondSectClose, empty, empty, &enc), XmlRole::None);
+        assert_eq!(state.token_role(XmlTok::Comment, empty, empty, &enc), XmlRole::Comment);
+    }
+
+    #[test]
+    #[cfg(feature = "DTD")]
+    fn test_content_model_allows_param_entity_ref_inside_a_group() {
+        // <!ELEMENT e (%inner; | b)>, as seen while scanning a parameter
+        // entity's own replacement text (document_entity == false).
+        let enc = Encoding::utf8();
+        let mut state = PrologState::new();
+        state.document_entity = false;
+        state.handler = element1;
+
+        assert_eq!(state.token_role(XmlTok::OpenParen, "", "", &enc), XmlRole::GroupOpen);
+        assert_eq!(
+            state.token_role(XmlTok::ParamEntityRef, "", "", &enc),
+            XmlRole::ContentParamEntityRef
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "DTD")]
+    fn test_content_model_whole_model_param_entity_ref_falls_back_to_inner_param_entity_ref() {
+        // <!ELEMENT e %model;>: element1 has no ParamEntityRef arm of its
+        // own, so this goes through common()'s pre-existing special case
+        // instead of the element2/4/6/7 ContentParamEntityRef arms.
+        let enc = Encoding::utf8();
+        let mut state = PrologState::new();
+        state.document_entity = false;
+        state.handler = element1;
+
+        let role = state.token_role(XmlTok::ParamEntityRef, "", "", &enc);
+        assert_eq!(role, XmlRole::InnerParamEntityRef);
+        assert_eq!(state.last_error(), None);
+    }
+
+    #[test]
+    fn test_role_scanner_yields_roles_for_a_doctype_declaration() {
+        let enc = Encoding::utf8();
+        let mut scanner = RoleScanner::new("<!DOCTYPE e>", enc);
+        let roles: Vec<XmlRole> = scanner.by_ref().map(|(role, _text)| role).collect();
+        assert_eq!(
+            roles,
+            vec![
+                XmlRole::DoctypeNone,
+                XmlRole::DoctypeNone,
+                XmlRole::DoctypeName,
+                XmlRole::DoctypeClose,
+            ]
+        );
+        assert_eq!(scanner.last_error(), None);
+    }
+
+    #[test]
+    fn test_role_scanner_stops_and_records_last_error_on_malformed_doctype() {
+        let enc = Encoding::utf8();
+        let mut scanner = RoleScanner::new("<!DOCTYPE>", enc);
+        let roles: Vec<XmlRole> = scanner.by_ref().map(|(role, _text)| role).collect();
+        assert_eq!(roles, vec![XmlRole::DoctypeNone, XmlRole::Error]);
+        assert_eq!(scanner.last_error(), Some(PrologError::MissingDoctypeName));
+    }
+
+    #[test]
+    fn test_group_connector_rejects_mixed_tokens() {
+        let mut state = PrologState::new();
+        state.push_group();
+        assert!(state.check_connector(GroupConnector::Sequence));
+        assert!(!state.check_connector(GroupConnector::Choice));
+        assert!(state.check_connector(GroupConnector::Sequence));
+    }
+
+    #[test]
+    fn test_group_connector_nested_groups_are_independent() {
+        let mut state = PrologState::new();
+        state.push_group();
+        assert!(state.check_connector(GroupConnector::Choice));
+        state.push_group();
+        assert!(state.check_connector(GroupConnector::Sequence));
+        state.pop_group();
+        assert!(state.check_connector(GroupConnector::Choice));
+    }
+
+    #[test]
+    fn test_common_records_last_error() {
+        let mut state = PrologState::new();
+        assert_eq!(state.last_error(), None);
+        let role = common(&mut state, XmlTok::Comma, PrologError::BadElementContent);
+        assert_eq!(role, XmlRole::Error);
+        assert_eq!(state.last_error(), Some(PrologError::BadElementContent));
+    }
+
+    // Same feature as test_group_connector_rejects_mixed_tokens/
+    // test_group_connector_nested_groups_are_independent above, at the
+    // token_role level instead of calling check_connector directly.
+    #[test]
+    fn test_rejects_mixed_connectors_like_a_comma_b_or_c() {
+        // <!ELEMENT e (a, b | c)>, driven through the real element1/2/6/7
+        // handlers rather than poking `check_connector` directly.
+        let enc = Encoding::utf8();
+        let mut state = PrologState::new();
+        state.handler = element1;
+
+        let buf = "(a,b|c)";
+        assert_eq!(
+            state.token_role(XmlTok::OpenParen, &buf[0..], &buf[buf.len()..], &enc),
+            XmlRole::GroupOpen
+        );
+        assert_eq!(
+            state.token_role(XmlTok::Name, &buf[1..2], &buf[buf.len()..], &enc),
+            XmlRole::ContentElement
+        );
+        assert_eq!(
+            state.token_role(XmlTok::Comma, &buf[2..3], &buf[buf.len()..], &enc),
+            XmlRole::GroupSequence
+        );
+        assert_eq!(
+            state.token_role(XmlTok::Name, &buf[3..4], &buf[buf.len()..], &enc),
+            XmlRole::ContentElement
+        );
+        let role = state.token_role(XmlTok::Or, &buf[4..5], &buf[buf.len()..], &enc);
+        assert_eq!(role, XmlRole::Error);
+        assert_eq!(state.last_error(), Some(PrologError::MixedGroupConnectors));
+    }
+
+    #[test]
+    fn test_allows_nested_group_with_its_own_connector() {
+        // <!ELEMENT e (a | (b, c))>, driven through the real element
+        // handlers; the inner group's `,` must not conflict with the
+        // outer group's `|`.
+        let enc = Encoding::utf8();
+        let mut state = PrologState::new();
+        state.handler = element1;
+
+        let buf = "(a|(b,c))";
+        assert_eq!(
+            state.token_role(XmlTok::OpenParen, &buf[0..], &buf[buf.len()..], &enc),
+            XmlRole::GroupOpen
+        );
+        assert_eq!(
+            state.token_role(XmlTok::Name, &buf[1..2], &buf[buf.len()..], &enc),
+            XmlRole::ContentElement
+        );
+        assert_eq!(
+            state.token_role(XmlTok::Or, &buf[2..3], &buf[buf.len()..], &enc),
+            XmlRole::GroupChoice
+        );
+        assert_eq!(
+            state.token_role(XmlTok::OpenParen, &buf[3..4], &buf[buf.len()..], &enc),
+            XmlRole::GroupOpen
+        );
+        assert_eq!(
+            state.token_role(XmlTok::Name, &buf[4..5], &buf[buf.len()..], &enc),
+            XmlRole::ContentElement
+        );
+        assert_eq!(
+            state.token_role(XmlTok::Comma, &buf[5..6], &buf[buf.len()..], &enc),
+            XmlRole::GroupSequence
+        );
+        assert_eq!(
+            state.token_role(XmlTok::Name, &buf[6..7], &buf[buf.len()..], &enc),
+            XmlRole::ContentElement
+        );
+        assert_eq!(
+            state.token_role(XmlTok::CloseParen, &buf[7..8], &buf[buf.len()..], &enc),
+            XmlRole::GroupClose
+        );
+        assert_eq!(
+            state.token_role(XmlTok::CloseParen, &buf[8..9], &buf[buf.len()..], &enc),
+            XmlRole::GroupClose
+        );
+        assert_eq!(state.last_error(), None);
+    }
+
+    #[test]
+    fn test_record_enum_token_rejects_duplicates() {
+        let mut state = PrologState::new();
+        assert!(state.record_enum_token("a"));
+        assert!(state.record_enum_token("b"));
+        assert!(!state.record_enum_token("a"));
+        state.reset_enum_tokens();
+        assert!(state.record_enum_token("a"));
+    }
+
+    #[test]
+    fn test_attlist_enumerated_type_rejects_duplicate_nmtoken_via_token_role() {
+        // <!ATTLIST e attr (a|a) ...>, driven through the real attlist2/3/4
+        // handlers rather than poking `record_enum_token` directly, so the
+        // `enum_token_text`/`Encoding::name_length` wiring is exercised too.
+        let enc = Encoding::utf8();
+        let mut state = PrologState::new();
+        state.handler = attlist2;
+
+        let buf = "(a|a)";
+        assert_eq!(
+            state.token_role(XmlTok::OpenParen, &buf[0..], &buf[buf.len()..], &enc),
+            XmlRole::AttlistNone
+        );
+        assert_eq!(
+            state.token_role(XmlTok::Nmtoken, &buf[1..2], &buf[buf.len()..], &enc),
+            XmlRole::AttributeEnumValue
+        );
+        assert_eq!(
+            state.token_role(XmlTok::Or, &buf[2..3], &buf[buf.len()..], &enc),
+            XmlRole::AttlistNone
+        );
+        let role = state.token_role(XmlTok::Nmtoken, &buf[3..4], &buf[buf.len()..], &enc);
+        assert_eq!(role, XmlRole::Error);
+        assert_eq!(state.last_error(), Some(PrologError::BadAttlistType));
+    }
+
+    #[test]
+    fn test_notation_list_rejects_duplicate_name_via_token_role() {
+        // <!ATTLIST e attr NOTATION (n|n) ...>, driven through the real
+        // attlist5/6/7 handlers the same way.
+        let enc = Encoding::utf8();
+        let mut state = PrologState::new();
+        state.handler = attlist5;
+
+        let buf = "(n|n)";
+        assert_eq!(
+            state.token_role(XmlTok::OpenParen, &buf[0..], &buf[buf.len()..], &enc),
+            XmlRole::AttlistNone
+        );
+        assert_eq!(
+            state.token_role(XmlTok::Name, &buf[1..2], &buf[buf.len()..], &enc),
+            XmlRole::AttributeNotationValue
+        );
+        assert_eq!(
+            state.token_role(XmlTok::Or, &buf[2..3], &buf[buf.len()..], &enc),
+            XmlRole::AttlistNone
+        );
+        let role = state.token_role(XmlTok::Name, &buf[3..4], &buf[buf.len()..], &enc);
+        assert_eq!(role, XmlRole::Error);
+        assert_eq!(state.last_error(), Some(PrologError::BadAttlistType));
+    }
+
+    #[test]
+    fn test_push_group_rejects_past_max_depth() {
+        let mut state = PrologState::new();
+        state.set_max_group_depth(2);
+        assert!(state.push_group());
+        assert!(state.push_group());
+        assert!(!state.push_group());
+    }
+
+    #[test]
+    fn test_element_content_model_depth_exceeded_via_token_role() {
+        // <!ELEMENT e (((a)))>, with max_group_depth lowered to 2, driven
+        // through the real element1/2/6 handlers rather than calling
+        // push_group directly.
+        let enc = Encoding::utf8();
+        let mut state = PrologState::new();
+        state.set_max_group_depth(2);
+        state.handler = element1;
+
+        assert_eq!(state.token_role(XmlTok::OpenParen, "", "", &enc), XmlRole::GroupOpen);
+        assert_eq!(state.token_role(XmlTok::OpenParen, "", "", &enc), XmlRole::GroupOpen);
+        let role = state.token_role(XmlTok::OpenParen, "", "", &enc);
+        assert_eq!(role, XmlRole::Error);
+        assert_eq!(state.last_error(), Some(PrologError::GroupDepthExceeded));
+
+        // The handler has latched onto `error`; it swallows further tokens.
+        assert_eq!(state.token_role(XmlTok::Name, "", "", &enc), XmlRole::None);
+    }
+
+    #[test]
+    fn test_content_model_builder_empty_and_any() {
+        let mut empty = ContentModelBuilder::new();
+        empty.push(XmlRole::ContentEmpty, "EMPTY");
+        assert_eq!(empty.content_model(), Some(&ContentModel::Empty));
+
+        let mut any = ContentModelBuilder::new();
+        any.push(XmlRole::ContentAny, "ANY");
+        assert_eq!(any.content_model(), Some(&ContentModel::Any));
+    }
+
+    #[test]
+    fn test_content_model_builder_mixed() {
+        // (#PCDATA | a | b)*
+        let mut builder = ContentModelBuilder::new();
+        builder.push(XmlRole::GroupOpen, "(");
+        builder.push(XmlRole::ContentPcdata, "#PCDATA");
+        builder.push(XmlRole::ContentElement, "a");
+        builder.push(XmlRole::ContentElement, "b");
+        builder.push(XmlRole::GroupCloseRep, ")*");
+        assert_eq!(
+            builder.content_model(),
+            Some(&ContentModel::Mixed(vec!["a".to_string(), "b".to_string()]))
+        );
+    }
+
+    #[test]
+    fn test_content_model_builder_sequence() {
+        // (a, b, c)
+        let mut builder = ContentModelBuilder::new();
+        builder.push(XmlRole::GroupOpen, "(");
+        builder.push(XmlRole::ContentElement, "a");
+        builder.push(XmlRole::GroupSequence, ",");
+        builder.push(XmlRole::ContentElement, "b");
+        builder.push(XmlRole::GroupSequence, ",");
+        builder.push(XmlRole::ContentElement, "c");
+        builder.push(XmlRole::GroupClose, ")");
+        assert_eq!(
+            builder.content_model(),
+            Some(&ContentModel::Sequence(
+                vec![
+                    ContentModel::Name("a".to_string(), ContentQuantifier::Once),
+                    ContentModel::Name("b".to_string(), ContentQuantifier::Once),
+                    ContentModel::Name("c".to_string(), ContentQuantifier::Once),
+                ],
+                ContentQuantifier::Once
+            ))
+        );
+    }
+
+    #[test]
+    fn test_content_model_builder_strips_quantifier_suffix_from_leaf_text() {
+        // (a?, b*, c+)
+        let mut builder = ContentModelBuilder::new();
+        builder.push(XmlRole::GroupOpen, "(");
+        builder.push(XmlRole::ContentElementOpt, "a?");
+        builder.push(XmlRole::GroupSequence, ",");
+        builder.push(XmlRole::ContentElementRep, "b*");
+        builder.push(XmlRole::GroupSequence, ",");
+        builder.push(XmlRole::ContentElementPlus, "c+");
+        builder.push(XmlRole::GroupClose, ")");
+        assert_eq!(
+            builder.content_model(),
+            Some(&ContentModel::Sequence(
+                vec![
+                    ContentModel::Name("a".to_string(), ContentQuantifier::Optional),
+                    ContentModel::Name("b".to_string(), ContentQuantifier::Repeated),
+                    ContentModel::Name("c".to_string(), ContentQuantifier::OneOrMore),
+                ],
+                ContentQuantifier::Once
+            ))
+        );
+    }
+
+    #[test]
+    fn test_content_model_builder_nested_choice_and_sequence() {
+        // (a | (b, c))+
+        let mut builder = ContentModelBuilder::new();
+        builder.push(XmlRole::GroupOpen, "(");
+        builder.push(XmlRole::ContentElement, "a");
+        builder.push(XmlRole::GroupChoice, "|");
+        builder.push(XmlRole::GroupOpen, "(");
+        builder.push(XmlRole::ContentElement, "b");
+        builder.push(XmlRole::GroupSequence, ",");
+        builder.push(XmlRole::ContentElement, "c");
+        builder.push(XmlRole::GroupClose, ")");
+        builder.push(XmlRole::GroupClosePlus, ")+");
+        assert_eq!(
+            builder.content_model(),
+            Some(&ContentModel::Choice(
+                vec![
+                    ContentModel::Name("a".to_string(), ContentQuantifier::Once),
+                    ContentModel::Sequence(
+                        vec![
+                            ContentModel::Name("b".to_string(), ContentQuantifier::Once),
+                            ContentModel::Name("c".to_string(), ContentQuantifier::Once),
+                        ],
+                        ContentQuantifier::Once
+                    ),
+                ],
+                ContentQuantifier::OneOrMore
+            ))
+        );
+    }
 }
\ No newline at end of file